@@ -3,11 +3,229 @@ use dprint_plugin_json;
 use dprint_plugin_markdown;
 use dprint_plugin_markdown::configuration::TextWrap;
 use dprint_plugin_typescript;
-use dprint_plugin_typescript::configuration::{QuoteProps, SortOrder};
-use glob::glob;
+use dprint_plugin_typescript::configuration::{QuoteProps, QuoteStyle, SemiColons, SortOrder};
+use deno_core::serde::Deserialize;
+use std::collections::HashSet;
 use std::ffi::OsStr;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
+/// On-disk cache mapping a hash of (config, path, source bytes) to "already
+/// formatted" state so repeat `fmt` runs skip files that have not changed.
+///
+/// `known` is the set loaded from the previous run; `seen` accumulates every key
+/// observed during the current run (cache hits plus freshly formatted files).
+/// `save` persists only `seen`, so keys belonging to files that were deleted,
+/// edited, or formatted under a superseded config fall out and the store does
+/// not grow without bound.
+///
+/// Only a 64-bit hash is stored — no path or mtime — so a hash collision would
+/// make `fmt` skip a file that actually needs formatting. This is accepted as
+/// astronomically unlikely over a single tree for the space it saves; a later
+/// `fmt` after any further edit re-hashes and corrects it.
+struct FormatCache {
+    path: PathBuf,
+    known: HashSet<u64>,
+    seen: HashSet<u64>,
+}
+
+impl FormatCache {
+    fn key(config: u64, path: &Path, contents: &str) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        config.hash(&mut hasher);
+        path.hash(&mut hasher);
+        contents.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Load the cache from its well-known location, or start empty if it is
+    /// missing or unreadable.
+    fn load() -> Self {
+        let path = cache_file();
+        let mut known = HashSet::new();
+        if let Ok(text) = std::fs::read_to_string(&path) {
+            for line in text.lines() {
+                if let Ok(hash) = line.parse::<u64>() {
+                    known.insert(hash);
+                }
+            }
+        }
+        Self {
+            path,
+            known,
+            seen: HashSet::new(),
+        }
+    }
+
+    fn contains(&self, key: u64) -> bool {
+        self.known.contains(&key) || self.seen.contains(&key)
+    }
+
+    fn insert(&mut self, key: u64) {
+        self.seen.insert(key);
+    }
+
+    /// Persist the keys observed this run, rewriting the file so stale entries
+    /// are pruned. Skips the write when nothing changed.
+    fn save(&self) -> Result<(), AnyError> {
+        if self.seen == self.known {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut out = String::new();
+        for hash in &self.seen {
+            out.push_str(&hash.to_string());
+            out.push('\n');
+        }
+        std::fs::write(&self.path, out)?;
+        Ok(())
+    }
+}
+
+/// Resolve the path of the formatter cache file under the user cache dir,
+/// falling back to the system temp dir when no home is available.
+fn cache_file() -> PathBuf {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    base.join("bueno").join("fmt-cache")
+}
+
+/// How prose (Markdown text) should be wrapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(crate = "deno_core::serde", rename_all = "lowercase")]
+pub enum ProseWrap {
+    Always,
+    Never,
+    Preserve,
+}
+
+impl Default for ProseWrap {
+    fn default() -> Self {
+        ProseWrap::Always
+    }
+}
+
+/// Per-language override of the top-level formatter options. Every field is
+/// optional so that a partial override (e.g. only `singleQuote`) leaves the
+/// remaining options inherited from the top-level config rather than reset to
+/// the global defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "deno_core::serde", rename_all = "camelCase", default)]
+pub struct FmtOverride {
+    pub use_tabs: Option<bool>,
+    pub line_width: Option<u32>,
+    pub indent_width: Option<u8>,
+    pub single_quote: Option<bool>,
+    pub prose_wrap: Option<ProseWrap>,
+    pub semi_colons: Option<bool>,
+}
+
+/// The fully resolved set of options for a single language, after layering any
+/// per-language override onto the top-level config.
+pub struct ResolvedFmt {
+    pub use_tabs: bool,
+    pub line_width: u32,
+    pub indent_width: u8,
+    pub single_quote: bool,
+    pub prose_wrap: ProseWrap,
+    pub semi_colons: bool,
+}
+
+/// Formatting options sourced from the `fmt` section of a project config file,
+/// with per-language overrides. Field defaults reproduce bueno's opinionated
+/// built-in formatting so an empty/absent config behaves as before.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "deno_core::serde", rename_all = "camelCase", default)]
+pub struct FmtConfig {
+    pub use_tabs: bool,
+    pub line_width: u32,
+    pub indent_width: u8,
+    pub single_quote: bool,
+    pub prose_wrap: ProseWrap,
+    pub semi_colons: bool,
+    /// Per-language overrides layered on top of the top-level options.
+    pub typescript: FmtOverride,
+    pub json: FmtOverride,
+    pub markdown: FmtOverride,
+}
+
+impl Default for FmtConfig {
+    fn default() -> Self {
+        FmtConfig {
+            use_tabs: true,
+            line_width: 80,
+            indent_width: 2,
+            single_quote: false,
+            prose_wrap: ProseWrap::Always,
+            semi_colons: true,
+            typescript: FmtOverride::default(),
+            json: FmtOverride::default(),
+            markdown: FmtOverride::default(),
+        }
+    }
+}
+
+impl FmtConfig {
+    /// Load the `fmt` section from `bueno.json` in the current directory,
+    /// falling back to defaults when the file or section is absent.
+    pub fn load() -> Result<FmtConfig, AnyError> {
+        #[derive(Deserialize)]
+        #[serde(crate = "deno_core::serde")]
+        struct ProjectConfig {
+            fmt: Option<FmtConfig>,
+        }
+
+        match std::fs::read_to_string("bueno.json") {
+            Ok(text) => {
+                let project: ProjectConfig = deno_core::serde_json::from_str(&text)?;
+                Ok(project.fmt.unwrap_or_default())
+            }
+            Err(_) => Ok(FmtConfig::default()),
+        }
+    }
+
+    /// Resolve the effective options for `lang` by layering the matching
+    /// per-language override (if any) over the top-level options. Only the
+    /// fields the user actually set in the override are applied; everything
+    /// else is inherited from the top-level config.
+    fn for_lang(&self, lang: &str) -> ResolvedFmt {
+        let ov = match lang {
+            "typescript" => self.typescript.clone(),
+            "json" => self.json.clone(),
+            "markdown" => self.markdown.clone(),
+            _ => FmtOverride::default(),
+        };
+        ResolvedFmt {
+            use_tabs: ov.use_tabs.unwrap_or(self.use_tabs),
+            line_width: ov.line_width.unwrap_or(self.line_width),
+            indent_width: ov.indent_width.unwrap_or(self.indent_width),
+            single_quote: ov.single_quote.unwrap_or(self.single_quote),
+            prose_wrap: ov.prose_wrap.unwrap_or(self.prose_wrap),
+            semi_colons: ov.semi_colons.unwrap_or(self.semi_colons),
+        }
+    }
+
+    /// A stable hash of the resolved options, used to key the incremental cache.
+    fn signature(&self) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        for lang in ["typescript", "json", "markdown"] {
+            let cfg = self.for_lang(lang);
+            cfg.use_tabs.hash(&mut hasher);
+            cfg.line_width.hash(&mut hasher);
+            cfg.indent_width.hash(&mut hasher);
+            cfg.single_quote.hash(&mut hasher);
+            (cfg.prose_wrap as u8).hash(&mut hasher);
+            cfg.semi_colons.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
 // TODO(lino-levan): Make typescript/json/markdown global static variables when `LazyCell` is stable.
 // https://doc.rust-lang.org/std/cell/struct.LazyCell.html
 
@@ -16,13 +234,30 @@ fn fake_path(ext: &str) -> PathBuf {
     PathBuf::from(file_name)
 }
 
-fn format_typescript_file(path: &Path, contents: &str) -> Result<Option<String>, AnyError> {
+fn format_typescript_file(
+    path: &Path,
+    contents: &str,
+    config: &FmtConfig,
+) -> Result<Option<String>, AnyError> {
+    let lang = config.for_lang("typescript");
     dprint_plugin_typescript::format_text(
         path,
         contents,
         &dprint_plugin_typescript::configuration::ConfigurationBuilder::new()
             .deno()
-            .use_tabs(true)
+            .use_tabs(lang.use_tabs)
+            .line_width(lang.line_width)
+            .indent_width(lang.indent_width)
+            .quote_style(if lang.single_quote {
+                QuoteStyle::PreferSingle
+            } else {
+                QuoteStyle::PreferDouble
+            })
+            .semi_colons(if lang.semi_colons {
+                SemiColons::Prefer
+            } else {
+                SemiColons::Asi
+            })
             .quote_props(QuoteProps::AsNeeded)
             .comment_line_force_space_after_slashes(true)
             .ignore_node_comment_text("bueno-fmt-ignore")
@@ -33,37 +268,174 @@ fn format_typescript_file(path: &Path, contents: &str) -> Result<Option<String>,
     )
 }
 
-fn format_json_file(contents: &str) -> Result<Option<String>, AnyError> {
+fn format_json_file(contents: &str, config: &FmtConfig) -> Result<Option<String>, AnyError> {
+    let lang = config.for_lang("json");
     dprint_plugin_json::format_text(
         &contents,
         &dprint_plugin_json::configuration::ConfigurationBuilder::new()
-            .line_width(80)
-            .use_tabs(true)
+            .line_width(lang.line_width)
+            .use_tabs(lang.use_tabs)
+            .indent_width(lang.indent_width)
             .ignore_node_comment_text("bueno-fmt-ignore")
             .comment_line_force_space_after_slashes(true)
             .build(),
     )
 }
 
-fn format_markdown_file(contents: &str) -> Result<Option<String>, AnyError> {
+fn format_markdown_file(contents: &str, config: &FmtConfig) -> Result<Option<String>, AnyError> {
+    let lang = config.for_lang("markdown");
+    let text_wrap = match lang.prose_wrap {
+        ProseWrap::Always => TextWrap::Always,
+        ProseWrap::Never => TextWrap::Never,
+        ProseWrap::Preserve => TextWrap::Maintain,
+    };
     dprint_plugin_markdown::format_text(
         &contents,
         &dprint_plugin_markdown::configuration::ConfigurationBuilder::new()
-            .text_wrap(TextWrap::Always)
+            .text_wrap(text_wrap)
+            .line_width(lang.line_width)
             .ignore_directive("bueno-fmt-ignore")
             .ignore_start_directive("bueno-fmt-ignore-start")
             .ignore_end_directive("bueno-fmt-ignore-end")
             .ignore_file_directive("bueno-fmt-ignore-file")
             .build(),
-        |tag, text, _line_number| format_file(tag, text),
+        |tag, text, _line_number| format_file(tag, text, config),
     )
 }
 
-fn format_file(ext: &str, contents: &str) -> Result<Option<String>, AnyError> {
+// ANSI colors used when rendering a `--check` diff to the terminal.
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Number of unchanged context lines to keep around each changed region.
+const DIFF_CONTEXT: usize = 3;
+
+/// A single line-oriented diff operation between the old and new text.
+enum DiffOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Compute a line-oriented diff between `old` and `new` using Myers' algorithm.
+/// The trace keeps one endpoint array per edit-distance step (O(D·(n+m)) space
+/// instead of the O(n·m) of a full LCS table), so large source files diff
+/// without exhausting memory. Returns the ops in order alongside the line text.
+fn diff_lines<'a>(old: &'a [&'a str], new: &'a [&'a str]) -> Vec<(DiffOp, &'a str)> {
+    let (n, m) = (old.len(), new.len());
+    let max = n + m;
+    if max == 0 {
+        return Vec::new();
+    }
+    // Index a diagonal `k` in `[-max, max]` into a flat array.
+    let idx = |k: isize| (k + max as isize) as usize;
+
+    // Forward pass: record the furthest-reaching endpoint array at each step.
+    let mut v = vec![0isize; 2 * max + 1];
+    let mut trace = Vec::new();
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+        let mut k = -d;
+        while k <= d {
+            // Pick the neighbour that reached further: down (insert) or right (delete).
+            let mut x = if k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+                v[idx(k + 1)]
+            } else {
+                v[idx(k - 1)] + 1
+            };
+            let mut y = x - k;
+            // Follow the diagonal of equal lines (the "snake").
+            while (x as usize) < n && (y as usize) < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx(k)] = x;
+            if x as usize >= n && y as usize >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    // Backtrack through the trace, emitting ops from end to start.
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n as isize, m as isize);
+    for d in (0..trace.len() as isize).rev() {
+        let v = &trace[d as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[idx(prev_k)];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push((DiffOp::Equal, old[(x - 1) as usize]));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                ops.push((DiffOp::Insert, new[(prev_y) as usize]));
+            } else {
+                ops.push((DiffOp::Delete, old[(prev_x) as usize]));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+
+    ops.reverse();
+    ops
+}
+
+/// Print a colored, context-limited diff of the formatting changes for `path`.
+fn print_diff(path: &Path, old: &str, new: &str) {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    // Mark which ops to keep: every change plus `DIFF_CONTEXT` lines around it.
+    let mut keep = vec![false; ops.len()];
+    for (idx, (op, _)) in ops.iter().enumerate() {
+        if !matches!(op, DiffOp::Equal) {
+            let lo = idx.saturating_sub(DIFF_CONTEXT);
+            let hi = (idx + DIFF_CONTEXT + 1).min(ops.len());
+            for k in lo..hi {
+                keep[k] = true;
+            }
+        }
+    }
+
+    println!("{}{}{}", DIM, path.display(), RESET);
+    let mut gap = false;
+    for (idx, (op, line)) in ops.iter().enumerate() {
+        if !keep[idx] {
+            gap = true;
+            continue;
+        }
+        if gap {
+            println!("{}...{}", DIM, RESET);
+            gap = false;
+        }
+        match op {
+            DiffOp::Equal => println!(" {}", line),
+            DiffOp::Delete => println!("{}-{}{}", RED, line, RESET),
+            DiffOp::Insert => println!("{}+{}{}", GREEN, line, RESET),
+        }
+    }
+}
+
+fn format_file(ext: &str, contents: &str, config: &FmtConfig) -> Result<Option<String>, AnyError> {
     match ext {
-        "js" | "ts" | "jsx" | "tsx" => format_typescript_file(fake_path(ext).as_path(), &contents),
-        "json" | "jsonc" => format_json_file(&contents),
-        "md" | "markdown" => format_markdown_file(&contents),
+        "js" | "ts" | "jsx" | "tsx" => {
+            format_typescript_file(fake_path(ext).as_path(), &contents, config)
+        }
+        "json" | "jsonc" => format_json_file(&contents, config),
+        "md" | "markdown" => format_markdown_file(&contents, config),
         _ => Ok(None),
     }
 }
@@ -71,29 +443,614 @@ fn format_file(ext: &str, contents: &str) -> Result<Option<String>, AnyError> {
 pub struct FormatOptions<'a> {
     pub check: bool,
     pub glob: &'a String,
+    /// Explicit language selector used when formatting stdin, which has no
+    /// path to infer an extension from (e.g. `Some("ts")`).
+    pub ext: Option<String>,
+    /// Keep running and re-format files as they change on disk.
+    pub watch: bool,
+    /// Glob patterns whose matches are excluded from traversal.
+    pub exclude: Vec<String>,
+    /// Resolved formatter configuration (see `FmtConfig::load`).
+    pub config: FmtConfig,
 }
 
-pub fn fmt(options: FormatOptions) -> Result<(), AnyError> {
-    for entry in glob(&options.glob)? {
-        match entry {
-            Ok(path) => match path.extension().and_then(OsStr::to_str) {
-                Some(
-                    ext @ ("js" | "ts" | "jsx" | "tsx" | "json" | "jsonc" | "md" | "markdown"),
-                ) => {
-                    let contents = std::fs::read_to_string(path.clone())?;
-
-                    if let Some(text) = format_file(ext, &contents)? {
-                        println!("fmt: {}", path.display());
-                        if !options.check {
-                            std::fs::write(path, text)?;
-                        }
+impl FormatOptions<'_> {
+    /// Build a `FileCollector` from the include glob and exclude patterns. An
+    /// empty or `.` glob means "current directory", collecting every supported
+    /// file below it. The traversal is rooted at the glob's literal base
+    /// directory so absolute and out-of-cwd patterns are honored.
+    fn collector(&self) -> Result<FileCollector, AnyError> {
+        let (include, root) = if self.glob.is_empty() || self.glob == "." {
+            (Vec::new(), PathBuf::from("."))
+        } else if Path::new(self.glob.as_str()).is_dir() {
+            // An explicit directory argument: walk it with no include filter.
+            (Vec::new(), PathBuf::from(self.glob.as_str()))
+        } else {
+            (vec![self.glob.clone()], glob_root(self.glob))
+        };
+        FileCollector::new(&include, &self.exclude, root)
+    }
+}
+
+/// Extract the literal base directory of a glob pattern — the longest leading
+/// run of components before the first wildcard — so traversal can start there
+/// rather than always at the current directory. Falls back to `.` for a
+/// purely relative pattern and to the containing directory when the base names
+/// a file.
+fn glob_root(pattern: &str) -> PathBuf {
+    use std::path::Component;
+
+    let mut base = PathBuf::new();
+    for comp in Path::new(pattern).components() {
+        match comp {
+            Component::Normal(s) => {
+                if s.to_string_lossy().contains(['*', '?', '[']) {
+                    break;
+                }
+                base.push(s);
+            }
+            Component::CurDir => {}
+            other => base.push(other.as_os_str()),
+        }
+    }
+
+    if base.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else if base.is_dir() {
+        base
+    } else {
+        match base.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+}
+
+/// Directories skipped by default during traversal regardless of the include
+/// patterns, so vendored and build output never gets formatted.
+const DEFAULT_IGNORED_DIRS: &[&str] = &[
+    "node_modules",
+    ".git",
+    "target",
+    "dist",
+    "build",
+    "coverage",
+    "vendor",
+];
+
+/// Strip a leading `./` so patterns like `src/**/*.ts` match paths produced by
+/// walking from the current directory.
+fn normalize(path: &Path) -> &Path {
+    path.strip_prefix("./").unwrap_or(path)
+}
+
+/// Match options for include/exclude patterns. `require_literal_separator` makes
+/// `*`/`?` stop at `/`, so `*.ts` only matches top-level files and crossing
+/// directories requires an explicit `**` — matching shell glob expectations.
+fn match_options() -> glob::MatchOptions {
+    glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    }
+}
+
+/// Walks a directory tree collecting files that match the include patterns and
+/// are not excluded, pattern-matching on the fly rather than expanding excludes
+/// into explicit file lists.
+struct FileCollector {
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+    root: PathBuf,
+}
+
+impl FileCollector {
+    /// Build a collector from raw include/exclude glob strings. An empty include
+    /// list matches every supported file (directory-default behavior). `root` is
+    /// the directory the walk starts from.
+    fn new(include: &[String], exclude: &[String], root: PathBuf) -> Result<Self, AnyError> {
+        let compile = |patterns: &[String]| -> Result<Vec<glob::Pattern>, AnyError> {
+            patterns
+                .iter()
+                .map(|p| glob::Pattern::new(p).map_err(AnyError::from))
+                .collect()
+        };
+        Ok(FileCollector {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+            root,
+        })
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        let path = normalize(path);
+        self.exclude
+            .iter()
+            .any(|p| p.matches_path_with(path, match_options()))
+    }
+
+    fn is_included(&self, path: &Path) -> bool {
+        let path = normalize(path);
+        self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|p| p.matches_path_with(path, match_options()))
+    }
+
+    /// Collect all matching files under the configured root, descending
+    /// recursively while honoring excludes and the default-ignored directory set.
+    fn collect(&self) -> Result<Vec<PathBuf>, AnyError> {
+        let mut out = Vec::new();
+        self.walk(&self.root, &mut out)?;
+        out.sort();
+        Ok(out)
+    }
+
+    fn walk(&self, dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), AnyError> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                let name = entry.file_name();
+                if DEFAULT_IGNORED_DIRS.iter().any(|d| name == **d) {
+                    continue;
+                }
+                if self.is_excluded(&path) {
+                    continue;
+                }
+                self.walk(&path, out)?;
+            } else if file_type.is_file()
+                && supported_ext(&path).is_some()
+                && self.is_included(&path)
+                && !self.is_excluded(&path)
+            {
+                out.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Extension this path should be formatted as, or `None` if it is not a
+/// supported source file.
+fn supported_ext(path: &Path) -> Option<&str> {
+    match path.extension().and_then(OsStr::to_str) {
+        Some(ext @ ("js" | "ts" | "jsx" | "tsx" | "json" | "jsonc" | "md" | "markdown")) => {
+            Some(ext)
+        }
+        _ => None,
+    }
+}
+
+/// The result of reading and formatting one file, before any cache update,
+/// write, or output has happened.
+enum Outcome {
+    /// Unsupported file — nothing to record.
+    Skipped,
+    /// Cache hit; `u64` is the content key to carry forward so it is not pruned.
+    Cached(u64),
+    /// Formatting left the file unchanged; `u64` is the content key to record.
+    AlreadyFormatted(u64),
+    /// The file needs reformatting. Carries the original and formatted text and
+    /// the content key of the formatted output.
+    Needs {
+        old: String,
+        new: String,
+        new_key: u64,
+    },
+}
+
+/// The Unicode byte-order-mark sometimes present at the start of files authored
+/// on Windows.
+const BOM: char = '\u{FEFF}';
+
+/// Split off a leading BOM, returning whether one was present and the remaining
+/// text. Formatting operates on the stripped text so the mark is preserved
+/// rather than folded into the formatted output.
+fn strip_bom(contents: &str) -> (bool, &str) {
+    match contents.strip_prefix(BOM) {
+        Some(rest) => (true, rest),
+        None => (false, contents),
+    }
+}
+
+/// Read and format one file without touching the cache or writing to disk.
+/// `cache_contains` answers whether a content key is already recorded so the
+/// expensive format step can be skipped on a hit.
+fn compute_outcome(
+    path: &Path,
+    options: &FormatOptions,
+    config_sig: u64,
+    cache_contains: impl Fn(u64) -> bool,
+) -> Result<Outcome, AnyError> {
+    let ext = match supported_ext(path) {
+        Some(ext) => ext,
+        None => return Ok(Outcome::Skipped),
+    };
+    let contents = std::fs::read_to_string(path)?;
+
+    let key = FormatCache::key(config_sig, path, &contents);
+    if cache_contains(key) {
+        return Ok(Outcome::Cached(key));
+    }
+
+    // Strip a leading BOM before formatting and re-prepend it to the result so
+    // formatting is BOM-preserving and does not produce spurious diffs.
+    let (had_bom, stripped) = strip_bom(&contents);
+    match format_file(ext, stripped, &options.config)? {
+        Some(text) => {
+            let new = if had_bom {
+                format!("{}{}", BOM, text)
+            } else {
+                text
+            };
+            let new_key = FormatCache::key(config_sig, path, &new);
+            Ok(Outcome::Needs {
+                old: contents,
+                new,
+                new_key,
+            })
+        }
+        None => Ok(Outcome::AlreadyFormatted(key)),
+    }
+}
+
+/// Format a single file, consulting and updating the incremental cache. Returns
+/// `true` if the file was (or would be) changed by formatting.
+fn format_path(
+    path: &Path,
+    options: &FormatOptions,
+    config_sig: u64,
+    cache: &mut FormatCache,
+) -> Result<bool, AnyError> {
+    match compute_outcome(path, options, config_sig, |k| cache.contains(k))? {
+        Outcome::Skipped => Ok(false),
+        Outcome::Cached(key) | Outcome::AlreadyFormatted(key) => {
+            cache.insert(key);
+            Ok(false)
+        }
+        Outcome::Needs { old, new, new_key } => {
+            if options.check {
+                print_diff(path, &old, &new);
+            } else {
+                println!("fmt: {}", path.display());
+                std::fs::write(path, &new)?;
+                cache.insert(new_key);
+            }
+            Ok(true)
+        }
+    }
+}
+
+/// Format `files` across a bounded pool of blocking worker threads sized to the
+/// available CPUs. Each worker reads, formats, and (when not in check mode)
+/// writes its file; cache updates and summary output are aggregated back on the
+/// main thread in input order. Any worker error aborts with a combined error.
+fn format_batch(
+    files: &[PathBuf],
+    options: &FormatOptions,
+    config_sig: u64,
+    cache: &mut FormatCache,
+) -> Result<usize, AnyError> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    if files.is_empty() {
+        return Ok(0);
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<Outcome, String>>>> =
+        Mutex::new((0..files.len()).map(|_| None).collect());
+
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(files.len());
+
+    {
+        // Guards cache membership lookups during the parallel phase.
+        let cache_mx = Mutex::new(&*cache);
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    if idx >= files.len() {
+                        break;
                     }
+                    let path = &files[idx];
+                    let outcome =
+                        compute_outcome(path, options, config_sig, |k| {
+                            cache_mx.lock().unwrap().contains(k)
+                        });
+                    // Workers write their own file so only ordered output and
+                    // cache bookkeeping remain for the main thread.
+                    let stored = match outcome {
+                        Ok(Outcome::Needs { old, new, new_key }) if !options.check => {
+                            match std::fs::write(path, &new) {
+                                Ok(()) => Ok(Outcome::Needs { old, new, new_key }),
+                                Err(e) => Err(e.to_string()),
+                            }
+                        }
+                        Ok(o) => Ok(o),
+                        Err(e) => Err(e.to_string()),
+                    };
+                    results.lock().unwrap()[idx] = Some(stored);
+                });
+            }
+        });
+    }
+
+    let results = results.into_inner().unwrap();
+    let mut changed = 0usize;
+    let mut errors: Vec<String> = Vec::new();
+    for (idx, res) in results.into_iter().enumerate() {
+        match res.expect("every index is processed") {
+            Ok(Outcome::Skipped) => {}
+            Ok(Outcome::Cached(key)) | Ok(Outcome::AlreadyFormatted(key)) => cache.insert(key),
+            Ok(Outcome::Needs { old, new, new_key }) => {
+                changed += 1;
+                if options.check {
+                    print_diff(&files[idx], &old, &new);
+                } else {
+                    println!("fmt: {}", files[idx].display());
+                    cache.insert(new_key);
                 }
-                _ => {}
-            },
-            Err(e) => println!("{:?}", e),
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(AnyError::msg(errors.join("\n")));
+    }
+    Ok(changed)
+}
+
+/// Read source from stdin, format it, and either write the result to stdout or,
+/// under `check`, report whether it would change via the exit status.
+fn fmt_stdin(options: &FormatOptions) -> Result<(), AnyError> {
+    let ext = match &options.ext {
+        Some(ext) => ext.as_str(),
+        None => {
+            return Err(AnyError::msg(
+                "formatting stdin requires an explicit extension (e.g. --ext ts)",
+            ))
         }
+    };
+
+    // Reject an unsupported language up front so `None` unambiguously means
+    // "already formatted" rather than "nothing was checked".
+    if supported_ext(&fake_path(ext)).is_none() {
+        return Err(AnyError::msg(format!("unsupported extension: {}", ext)));
+    }
+
+    let mut contents = String::new();
+    std::io::stdin().read_to_string(&mut contents)?;
+
+    let (had_bom, stripped) = strip_bom(&contents);
+    let formatted = format_file(ext, stripped, &options.config)?;
+
+    if options.check {
+        if formatted.is_some() {
+            return Err(AnyError::msg("stdin is not formatted"));
+        }
+    } else {
+        let text = formatted.as_deref().unwrap_or(stripped);
+        if had_bom {
+            std::io::stdout().write_all(BOM.to_string().as_bytes())?;
+        }
+        std::io::stdout().write_all(text.as_bytes())?;
     }
 
     Ok(())
 }
+
+pub fn fmt(options: FormatOptions) -> Result<(), AnyError> {
+    if options.glob == "-" {
+        return fmt_stdin(&options);
+    }
+
+    let config_sig = options.config.signature();
+    let mut cache = FormatCache::load();
+    let collector = options.collector()?;
+
+    let files = collector.collect()?;
+    let changed = format_batch(&files, &options, config_sig, &mut cache)?;
+
+    cache.save()?;
+
+    if options.watch {
+        return watch(&options, config_sig, &mut cache);
+    }
+
+    if options.check && changed > 0 {
+        return Err(AnyError::msg(format!(
+            "{} file(s) would be reformatted",
+            changed
+        )));
+    }
+
+    Ok(())
+}
+
+/// Interval between filesystem scans in watch mode. Polling coalesces bursts of
+/// writes without depending on a platform-specific notification backend.
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(300);
+
+/// Last-modified time of `path`, falling back to the epoch when it cannot be read.
+fn modified(path: &Path) -> std::time::SystemTime {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .unwrap_or(std::time::UNIX_EPOCH)
+}
+
+/// Keep formatting the collected set as files change on disk. Each cycle
+/// re-resolves the include/exclude set (so new files are picked up) and
+/// re-formats only those whose modification time changed since the last pass.
+fn watch(
+    options: &FormatOptions,
+    config_sig: u64,
+    cache: &mut FormatCache,
+) -> Result<(), AnyError> {
+    use std::collections::HashMap;
+
+    // Seed with the current set; the initial pass already formatted everything.
+    let mut seen: HashMap<PathBuf, std::time::SystemTime> = options
+        .collector()?
+        .collect()?
+        .into_iter()
+        .map(|path| {
+            let mtime = modified(&path);
+            (path, mtime)
+        })
+        .collect();
+
+    println!("fmt: watching for changes...");
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+
+        let files = options.collector()?.collect()?;
+        let mut next: HashMap<PathBuf, std::time::SystemTime> = HashMap::new();
+        let mut changed = 0usize;
+        for path in files {
+            if seen.get(&path) != Some(&modified(&path))
+                && format_path(&path, options, config_sig, cache)?
+            {
+                changed += 1;
+            }
+            // Re-stat after any write so a just-formatted file is not re-processed.
+            next.insert(path.clone(), modified(&path));
+        }
+        seen = next;
+
+        cache.save()?;
+        if changed > 0 {
+            println!("fmt: reformatted {} file(s)", changed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collector(include: &[&str], exclude: &[&str]) -> FileCollector {
+        let include: Vec<String> = include.iter().map(|s| s.to_string()).collect();
+        let exclude: Vec<String> = exclude.iter().map(|s| s.to_string()).collect();
+        FileCollector::new(&include, &exclude, PathBuf::from(".")).unwrap()
+    }
+
+    #[test]
+    fn star_does_not_cross_directories() {
+        let c = collector(&["*.ts"], &[]);
+        assert!(c.is_included(Path::new("top.ts")));
+        assert!(c.is_included(Path::new("./top.ts")));
+        // `*` must not match across `/` — only `**` crosses directories.
+        assert!(!c.is_included(Path::new("sub/deep.ts")));
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        let c = collector(&["src/**/*.ts"], &[]);
+        assert!(c.is_included(Path::new("src/a.ts")));
+        assert!(c.is_included(Path::new("src/nested/b.ts")));
+        assert!(!c.is_included(Path::new("other/c.ts")));
+    }
+
+    #[test]
+    fn empty_include_matches_everything() {
+        let c = collector(&[], &[]);
+        assert!(c.is_included(Path::new("anything/at/all.ts")));
+    }
+
+    #[test]
+    fn excludes_respect_separators() {
+        let c = collector(&[], &["vendor/**"]);
+        assert!(c.is_excluded(Path::new("vendor/lib.ts")));
+        assert!(!c.is_excluded(Path::new("src/lib.ts")));
+    }
+
+    /// Render a diff as lines prefixed with ` `/`-`/`+` for easy assertions.
+    fn render_diff(old: &[&str], new: &[&str]) -> Vec<String> {
+        diff_lines(old, new)
+            .into_iter()
+            .map(|(op, line)| {
+                let prefix = match op {
+                    DiffOp::Equal => ' ',
+                    DiffOp::Delete => '-',
+                    DiffOp::Insert => '+',
+                };
+                format!("{}{}", prefix, line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diff_identical_is_all_equal() {
+        assert_eq!(render_diff(&["a", "b"], &["a", "b"]), vec![" a", " b"]);
+    }
+
+    #[test]
+    fn diff_reports_insertion() {
+        assert_eq!(
+            render_diff(&["a", "c"], &["a", "b", "c"]),
+            vec![" a", "+b", " c"]
+        );
+    }
+
+    #[test]
+    fn diff_reports_deletion() {
+        assert_eq!(
+            render_diff(&["a", "b", "c"], &["a", "c"]),
+            vec![" a", "-b", " c"]
+        );
+    }
+
+    #[test]
+    fn diff_reports_replacement() {
+        // A changed line is a delete followed by an insert.
+        assert_eq!(render_diff(&["a"], &["b"]), vec!["-a", "+b"]);
+    }
+
+    #[test]
+    fn diff_handles_empty_sides() {
+        assert_eq!(render_diff(&[], &["a"]), vec!["+a"]);
+        assert_eq!(render_diff(&["a"], &[]), vec!["-a"]);
+        assert!(render_diff(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn partial_override_inherits_top_level() {
+        let mut config = FmtConfig {
+            line_width: 100,
+            ..FmtConfig::default()
+        };
+        config.typescript.single_quote = Some(true);
+
+        let ts = config.for_lang("typescript");
+        // The override only set `single_quote`; everything else is inherited.
+        assert!(ts.single_quote);
+        assert_eq!(ts.line_width, 100);
+        assert!(ts.use_tabs);
+
+        // A language with no override inherits every top-level option.
+        let json = config.for_lang("json");
+        assert!(!json.single_quote);
+        assert_eq!(json.line_width, 100);
+    }
+
+    #[test]
+    fn strip_bom_round_trip() {
+        let with_bom = format!("{}let x = 1;", BOM);
+        let (had_bom, rest) = strip_bom(&with_bom);
+        assert!(had_bom);
+        assert_eq!(rest, "let x = 1;");
+
+        let (had_bom, rest) = strip_bom("let x = 1;");
+        assert!(!had_bom);
+        assert_eq!(rest, "let x = 1;");
+    }
+}